@@ -0,0 +1,174 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Accumulated damage tracking for the compositor.
+//!
+//! Compositing used to be gated on a single `dirty` bool that forced a full repaint of the
+//! whole surface on any change. Small updates -- a single tile arriving, a cursor blink --
+//! cost a full-screen redraw. Instead the message handlers union the screen rectangles they
+//! touch into a `DirtyRegion`; the compositor reads and clears the accumulated damage and
+//! repaints only those rectangles.
+//!
+//! A partial repaint is only worthwhile while the damage stays small. When the union grows to
+//! cover most of the surface -- or when a pinch-zoom changes the resolution, so every tile
+//! must be redrawn -- the region collapses to a full repaint.
+
+use std::util;
+use geom::rect::Rect;
+use geom::point::Point2D;
+use geom::size::Size2D;
+
+/// Once the accumulated damage covers this fraction of the surface, a partial repaint is no
+/// cheaper than a full one, so we fall back to repainting everything.
+static FULL_REPAINT_COVERAGE: f32 = 0.75;
+
+/// What a composite pass should repaint.
+pub enum Damage {
+    /// Nothing changed; skip the frame.
+    Nothing,
+    /// Repaint the whole surface.
+    Full,
+    /// Repaint only these screen-space rectangles.
+    Partial(~[Rect<int>]),
+}
+
+/// The damage accumulated since the last composite, in screen space.
+pub struct DirtyRegion {
+    /// The touched rectangles, or empty when `full` is set.
+    priv rects: ~[Rect<int>],
+    /// Set when the whole surface must be repainted; `rects` is then irrelevant.
+    priv full: bool,
+    /// The current surface size, used to decide when the union is large enough to promote to
+    /// a full repaint.
+    priv surface: Rect<int>,
+}
+
+impl DirtyRegion {
+    /// Creates an empty region for a surface of the given size.
+    pub fn new(width: int, height: int) -> DirtyRegion {
+        DirtyRegion {
+            rects: ~[],
+            full: false,
+            surface: Rect(Point2D(0, 0), Size2D(width, height)),
+        }
+    }
+
+    /// Updates the surface size, e.g. on a window resize.
+    pub fn set_surface(&mut self, width: int, height: int) {
+        self.surface = Rect(Point2D(0, 0), Size2D(width, height));
+    }
+
+    /// Whether there is any damage to repaint.
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.rects.is_empty()
+    }
+
+    /// Unions a touched rectangle into the region. Clipped to the surface; once the combined
+    /// coverage passes `FULL_REPAINT_COVERAGE` the region collapses to a full repaint.
+    pub fn add(&mut self, rect: Rect<int>) {
+        if self.full {
+            return;
+        }
+        match self.surface.intersection(&rect) {
+            Some(clipped) => self.rects.push(clipped),
+            None => return,
+        }
+        if self.covered_area() as f32 >= FULL_REPAINT_COVERAGE * self.surface_area() as f32 {
+            self.mark_full();
+        }
+    }
+
+    /// Forces the next composite to repaint the whole surface (e.g. after a resolution change
+    /// from a pinch-zoom, or a scroll that moves every pixel).
+    pub fn mark_full(&mut self) {
+        self.full = true;
+        self.rects.truncate(0);
+    }
+
+    /// Returns the accumulated damage and resets the region to empty.
+    pub fn take(&mut self) -> Damage {
+        if self.full {
+            self.full = false;
+            Full
+        } else if self.rects.is_empty() {
+            Nothing
+        } else {
+            let rects = util::replace(&mut self.rects, ~[]);
+            Partial(rects)
+        }
+    }
+
+    fn surface_area(&self) -> int {
+        self.surface.size.width * self.surface.size.height
+    }
+
+    /// A cheap upper bound on the damaged area: the sum of the rectangle areas, ignoring any
+    /// overlap between them. Overestimating only promotes to a full repaint sooner, which is
+    /// safe.
+    fn covered_area(&self) -> int {
+        let mut area = 0;
+        for self.rects.iter().advance |r| {
+            area += r.size.width * r.size.height;
+        }
+        area
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DirtyRegion, Nothing, Full, Partial};
+    use geom::rect::Rect;
+    use geom::point::Point2D;
+    use geom::size::Size2D;
+
+    fn rect(x: int, y: int, w: int, h: int) -> Rect<int> {
+        Rect(Point2D(x, y), Size2D(w, h))
+    }
+
+    #[test]
+    fn small_damage_stays_partial() {
+        let mut region = DirtyRegion::new(100, 100);
+        region.add(rect(0, 0, 10, 10));
+        region.add(rect(20, 20, 10, 10));
+        match region.take() {
+            Partial(rects) => assert_eq!(rects.len(), 2),
+            _ => fail!("expected a partial repaint"),
+        }
+        // Taking resets the region to empty.
+        match region.take() {
+            Nothing => {}
+            _ => fail!("expected no damage after take"),
+        }
+    }
+
+    #[test]
+    fn large_damage_promotes_to_full() {
+        // 80% of the surface in one rect is past FULL_REPAINT_COVERAGE (0.75).
+        let mut region = DirtyRegion::new(100, 100);
+        region.add(rect(0, 0, 100, 80));
+        match region.take() {
+            Full => {}
+            _ => fail!("expected a full repaint once coverage passes the threshold"),
+        }
+        // A full repaint is consumed by take() like any other damage.
+        match region.take() {
+            Nothing => {}
+            _ => fail!("expected no damage after take"),
+        }
+    }
+
+    #[test]
+    fn add_clips_to_surface() {
+        let mut region = DirtyRegion::new(100, 100);
+        region.add(rect(90, 90, 40, 40));
+        match region.take() {
+            Partial(rects) => {
+                assert_eq!(rects.len(), 1);
+                assert_eq!(rects[0].size.width, 10);
+                assert_eq!(rects[0].size.height, 10);
+            }
+            _ => fail!("expected a clipped partial repaint"),
+        }
+    }
+}
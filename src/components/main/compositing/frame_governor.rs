@@ -0,0 +1,119 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Frame pacing for the compositor.
+//!
+//! The main loop can wake many times a second, but compositing on *every* wakeup costs roughly
+//! 5% of a core for a page that is only updating occasionally. The governor decouples the
+//! composite cadence from the wakeup cadence: it tracks when the last frame was presented and
+//! coalesces a recomposite that is requested sooner than one frame interval after it,
+//! deferring the draw to the next frame boundary rather than running it eagerly.
+//!
+//! The cap is runtime-settable (see `SetFrameRate`) so the battery cost of a high refresh
+//! rate can be profiled against lower ones, and `Uncapped` restores the composite-on-every-
+//! wakeup behaviour for latency-sensitive profiling.
+
+/// Slack, in seconds, when comparing elapsed time against the frame interval.
+static FRAME_BOUNDARY_EPSILON: f64 = 1e-9;
+
+/// The configured compositing cap.
+pub enum FrameRate {
+    /// Present on every vblank the surface is dirty, with no additional pacing.
+    Uncapped,
+    /// Cap compositing to at most this many frames per second.
+    Capped(f32),
+}
+
+/// Paces compositing to the configured `FrameRate`. The main loop consults it on each wakeup
+/// before compositing, and `SetFrameRate` retunes the cap at runtime.
+pub struct FrameGovernor {
+    /// The current cap.
+    rate: FrameRate,
+    /// `precise_time_s()` of the last composite, or a negative sentinel before the first.
+    last_composite: f64,
+}
+
+impl FrameGovernor {
+    /// Creates a governor with the given cap that has not yet presented a frame.
+    pub fn new(rate: FrameRate) -> FrameGovernor {
+        FrameGovernor {
+            rate: rate,
+            last_composite: -1.0,
+        }
+    }
+
+    /// Retunes the cap. Takes effect on the next frame decision.
+    pub fn set_rate(&mut self, rate: FrameRate) {
+        self.rate = rate;
+    }
+
+    /// The minimum spacing between frames, in seconds, or `0.0` when uncapped.
+    fn frame_interval(&self) -> f64 {
+        match self.rate {
+            Uncapped => 0.0,
+            Capped(fps) if fps > 0.0 => 1.0 / (fps as f64),
+            Capped(_) => 0.0,
+        }
+    }
+
+    /// Whether a dirty surface may be composited at `now`. Uncapped always permits it; a
+    /// capped governor permits it only once a full frame interval has elapsed since the last
+    /// composite, so a burst of updates within one interval is coalesced into a single frame.
+    pub fn ready(&self, now: f64) -> bool {
+        if self.last_composite < 0.0 {
+            return true;
+        }
+        // Allow a tiny tolerance so a timestamp that lands on the frame boundary is not
+        // rejected by floating-point rounding.
+        now - self.last_composite + FRAME_BOUNDARY_EPSILON >= self.frame_interval()
+    }
+
+    /// Seconds until the next frame boundary, for arming a wakeup when a draw was coalesced.
+    /// `0.0` once the surface may be composited again.
+    pub fn time_until_ready(&self, now: f64) -> f64 {
+        if self.ready(now) {
+            0.0
+        } else {
+            self.frame_interval() - (now - self.last_composite)
+        }
+    }
+
+    /// Records that a frame was presented at `now`.
+    pub fn note_composite(&mut self, now: f64) {
+        self.last_composite = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FrameGovernor, Capped, Uncapped};
+
+    #[test]
+    fn coalesces_within_one_interval() {
+        // At 60 fps a frame interval is ~16.7 ms.
+        let mut governor = FrameGovernor::new(Capped(60.0));
+
+        // The very first frame is always allowed.
+        assert!(governor.ready(100.0));
+        governor.note_composite(100.0);
+
+        // A recomposite requested half an interval later is coalesced, and the governor
+        // reports the time remaining until the frame boundary.
+        assert!(!governor.ready(100.0 + 1.0 / 120.0));
+        let remaining = governor.time_until_ready(100.0 + 1.0 / 120.0);
+        assert!(remaining > 0.0 && remaining <= 1.0 / 60.0);
+
+        // Once a full interval has elapsed it may draw again, with no wait.
+        assert!(governor.ready(100.0 + 1.0 / 60.0));
+        assert_eq!(governor.time_until_ready(100.0 + 1.0 / 60.0), 0.0);
+    }
+
+    #[test]
+    fn uncapped_never_coalesces() {
+        let mut governor = FrameGovernor::new(Uncapped);
+        governor.note_composite(100.0);
+        assert!(governor.ready(100.0));
+        assert_eq!(governor.time_until_ready(100.0), 0.0);
+    }
+}
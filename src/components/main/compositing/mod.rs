@@ -3,9 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use platform::{Application, Window};
-use script::dom::event::{Event, ClickEvent, MouseDownEvent, MouseUpEvent, ResizeEvent};
-use script::script_task::{LoadMsg, NavigateMsg, SendEventMsg};
-use script::layout_interface::{LayoutChan, RouteScriptMsg};
+use compositing::script_traits::{ScriptChan, CompositorEvent, Cursor};
+use compositing::script_traits::{ResizeEvent, ClickEvent, MouseDownEvent, MouseUpEvent};
+use compositing::script_traits;
 use windowing::{ApplicationMethods, WindowMethods, WindowMouseEvent, WindowClickEvent};
 use windowing::{WindowMouseDownEvent, WindowMouseUpEvent};
 
@@ -13,27 +13,32 @@ use windowing::{WindowMouseDownEvent, WindowMouseUpEvent};
 use servo_msg::compositor_msg::{RenderListener, LayerBuffer, LayerBufferSet, RenderState};
 use servo_msg::compositor_msg::{ReadyState, ScriptListener};
 use servo_msg::constellation_msg::{CompositorAck, ConstellationChan};
-use servo_msg::constellation_msg;
 use gfx::render_task::{RenderChan, ReRenderMsg};
 
 use azure::azure_hl::{DataSourceSurface, DrawTarget, SourceSurfaceMethods, current_gl_context};
 use azure::azure::AzGLContext;
 use std::cell::Cell;
 use std::comm;
-use std::comm::{Chan, SharedChan, Port};
+use std::comm::{Chan, SharedChan, Port, Select};
+use std::hashmap::HashMap;
+use std::path::Path;
+use std::vec;
 use std::num::Orderable;
 use std::task;
-use extra::uv_global_loop;
 use extra::timer;
+use extra::uv_global_loop;
 use geom::matrix::identity;
-use geom::point::Point2D;
-use geom::size::Size2D;
+use geom::point::{Point2D, TypedPoint2D};
+use geom::size::{Size2D, TypedSize2D};
 use geom::rect::Rect;
+use geom::scale_factor::ScaleFactor;
 use layers::layers::{ARGB32Format, ContainerLayer, ContainerLayerKind, Format};
 use layers::layers::{ImageData, WithDataFn};
 use layers::layers::{TextureLayerKind, TextureLayer, TextureManager};
 use layers::rendergl;
 use layers::scene::Scene;
+use opengles::gl2;
+use png;
 use servo_util::{time, url};
 use servo_util::time::profile;
 use servo_util::time::ProfilerChan;
@@ -43,7 +48,45 @@ pub use windowing;
 
 use extra::time::precise_time_s;
 use compositing::quadtree::Quadtree;
+use compositing::scrolling::ScrollingAxis;
+use compositing::frame_governor::{FrameGovernor, FrameRate, Capped};
+use compositing::worker::{TileFetchWorker, Worker, WorkerSupervisor, WorkerState, Start};
+use compositing::dirty_region::{DirtyRegion, Damage, Nothing, Partial};
+use compositing::units::{PagePx, ScreenPx, DevicePixel};
 mod quadtree;
+mod scrolling;
+mod frame_governor;
+mod worker;
+mod dirty_region;
+pub mod script_traits;
+pub mod units;
+
+/// Identifies a layer within a pipeline. Each iframe or stacking context that scrolls and
+/// renders independently gets its own id.
+///
+/// FIXME: This should live in `servo_msg::compositor_msg` next to the other render protocol
+/// types; it rides along the `RenderListener` messages and is defined here until layout
+/// learns to mint ids.
+#[deriving(Clone, Eq, IterBytes)]
+pub struct LayerId(uint);
+
+/// A monotonically increasing tag identifying which layout generation produced a set of
+/// tiles. Paint messages carrying an epoch older than a layer's current epoch describe tiles
+/// from a superseded layout and are discarded.
+#[deriving(Clone, Eq, Ord)]
+pub struct Epoch(uint);
+
+impl Epoch {
+    /// Returns the next epoch.
+    pub fn next(&self) -> Epoch {
+        let Epoch(n) = *self;
+        Epoch(n + 1)
+    }
+}
+
+/// The id of the root layer of a pipeline, which the compositor creates implicitly until
+/// layout drives layer creation.
+static ROOT_LAYER_ID: LayerId = LayerId(0);
 
 /// The implementation of the layers-based compositor.
 #[deriving(Clone)]
@@ -71,18 +114,26 @@ impl RenderListener for CompositorChan {
         port.recv()
     }
 
-    fn paint(&self, id: uint, layer_buffer_set: arc::ARC<LayerBufferSet>, new_size: Size2D<uint>) {
-        self.chan.send(Paint(id, layer_buffer_set, new_size))
+    // `RenderListener` lives in `servo_msg` and is unchanged by this series, so these methods
+    // keep its single-layer signatures. The render protocol does not yet carry a layer id or
+    // epoch (see `LayerId`'s FIXME); until layout mints them, every message targets the root
+    // layer at epoch 0 and the richer per-layer `Msg` plumbing rides along internally.
+    fn paint(&self,
+             id: uint,
+             layer_buffer_set: arc::ARC<LayerBufferSet>,
+             new_size: Size2D<uint>) {
+        self.chan.send(Paint(id, ROOT_LAYER_ID, Epoch(0), layer_buffer_set,
+                             TypedSize2D::from_untyped(&new_size)))
     }
 
     fn new_layer(&self, page_size: Size2D<uint>, tile_size: uint) {
-        self.chan.send(NewLayer(page_size, tile_size))
+        self.chan.send(NewLayer(ROOT_LAYER_ID, TypedSize2D::from_untyped(&page_size), tile_size))
     }
     fn resize_layer(&self, page_size: Size2D<uint>) {
-        self.chan.send(ResizeLayer(page_size))
+        self.chan.send(ResizeLayer(ROOT_LAYER_ID, TypedSize2D::from_untyped(&page_size)))
     }
     fn delete_layer(&self) {
-        self.chan.send(DeleteLayer)
+        self.chan.send(DeleteLayer(ROOT_LAYER_ID))
     }
 
     fn set_render_state(&self, render_state: RenderState) {
@@ -102,11 +153,18 @@ impl CompositorChan {
         self.chan.send(msg);
     }
 
-    pub fn get_size(&self) -> Size2D<int> {
+    pub fn get_size(&self) -> TypedSize2D<DevicePixel, int> {
         let (port, chan) = comm::stream();
         self.chan.send(GetSize(chan));
         port.recv()
     }
+
+    /// Updates the window's cursor icon in response to a script-side hit test. This is not part
+    /// of the external `ScriptListener` trait, so script reaches it through the concrete
+    /// `CompositorChan` rather than the abstract listener handle.
+    pub fn set_cursor(&self, cursor: Cursor) {
+        self.chan.send(SetCursor(cursor));
+    }
 }
 
 /// Messages to the compositor.
@@ -114,26 +172,98 @@ pub enum Msg {
     /// Requests that the compositor shut down.
     Exit,
     /// Requests the window size
-    GetSize(Chan<Size2D<int>>),
+    GetSize(Chan<TypedSize2D<DevicePixel, int>>),
     /// Requests the compositors GL context.
     GetGLContext(Chan<AzGLContext>),
 
-    // TODO: Attach layer ids and epochs to these messages
     /// Alerts the compositor that there is a new layer to be rendered.
-    NewLayer(Size2D<uint>, uint),
-    /// Alerts the compositor that the current layer has changed size.
-    ResizeLayer(Size2D<uint>),
-    /// Alerts the compositor that the current layer has been deleted.
-    DeleteLayer,
+    NewLayer(LayerId, TypedSize2D<PagePx, uint>, uint),
+    /// Alerts the compositor that the given layer has changed size.
+    ResizeLayer(LayerId, TypedSize2D<PagePx, uint>),
+    /// Alerts the compositor that the given layer has been deleted.
+    DeleteLayer(LayerId),
 
     /// Requests that the compositor paint the given layer buffer set for the given page size.
-    Paint(uint, arc::ARC<LayerBufferSet>, Size2D<uint>),
+    /// The epoch tags which layout generation produced the buffers.
+    Paint(uint, LayerId, Epoch, arc::ARC<LayerBufferSet>, TypedSize2D<PagePx, uint>),
     /// Alerts the compositor to the current status of page loading.
     ChangeReadyState(ReadyState),
     /// Alerts the compositor to the current status of rendering.
     ChangeRenderState(RenderState),
-    /// Sets the channel to the current layout and render tasks, along with their id
-    SetLayoutRenderChans(LayoutChan, RenderChan , uint, ConstellationChan)
+    /// Requests that the composited framebuffer be read back and written to the given path as
+    /// a PNG, for headless rendering and reftests.
+    Screenshot(Path),
+    /// Sets the window's cursor icon, in response to a mouse-move hit test in script.
+    SetCursor(Cursor),
+    /// Retunes the compositing frame-rate cap at runtime (e.g. for profiling the cost of a
+    /// continuous 60 Hz draw against a battery-saving lower rate, or uncapped presentation).
+    SetFrameRate(FrameRate),
+    /// Requests a listing of the compositor's background workers and their states, for
+    /// debugging (e.g. zoom-stutter reports).
+    ListWorkers(Chan<~[(~str, WorkerState)]>),
+    /// Sets the channel to the current script and render tasks, along with their id
+    SetLayoutRenderChans(ScriptChan, RenderChan , uint, ConstellationChan)
+}
+
+/// The compositor's per-layer state: each layer owns its own tile quadtree, page size, and
+/// scroll offset, so iframes and stacking contexts can scroll and render independently.
+struct LayerState {
+    /// The tile quadtree for this layer.
+    quadtree: Quadtree<~LayerBuffer>,
+    /// The page size of this layer, in page pixels.
+    page_size: TypedSize2D<PagePx, f32>,
+    /// The scroll offset of this layer, in screen pixels.
+    scroll_offset: TypedPoint2D<ScreenPx, f32>,
+    /// The most recent layout generation whose tiles we have accepted. Paint messages with an
+    /// older epoch are stale and ignored.
+    epoch: Epoch,
+}
+
+/// Below this coverage ratio, `ask_for_tiles` fills the viewport with a cheap low-resolution
+/// pass before queueing the full-resolution request.
+static INTEGRITY_THRESHOLD: f32 = 0.95;
+
+/// The resolution of the coarse fill pass relative to the current zoom.
+static LOW_RES_SCALE: f32 = 0.5;
+
+impl LayerState {
+    /// Computes how completely the visible page rect is covered by valid tiles, as a ratio in
+    /// `[0, 1]` equal to (area covered by tiles whose resolution matches `world_zoom`) /
+    /// (total visible area).
+    ///
+    /// An empty or unscrollable quadtree returns `1.0` so the compositor does not spin
+    /// requesting tiles for a blank page.
+    fn render_integrity(&mut self, visible: Rect<int>, world_zoom: f32) -> f32 {
+        let total = (visible.size.width * visible.size.height) as f32;
+        if total <= 0.0 {
+            return 1.0;
+        }
+
+        let tiles = self.quadtree.get_all_tiles();
+        if tiles.is_empty() {
+            return 1.0;
+        }
+
+        let mut covered = 0f32;
+        for tiles.iter().advance |tile| {
+            // Only tiles rendered at exactly this zoom count as high-precision coverage.
+            if tile.resolution != world_zoom {
+                loop;
+            }
+            let tile_rect = Rect(Point2D(tile.screen_pos.origin.x as int,
+                                         tile.screen_pos.origin.y as int),
+                                 Size2D(tile.screen_pos.size.width as int,
+                                        tile.screen_pos.size.height as int));
+            match visible.intersection(&tile_rect) {
+                Some(overlap) => {
+                    covered += (overlap.size.width * overlap.size.height) as f32;
+                }
+                None => {}
+            }
+        }
+
+        (covered / total).clamp(&0.0, &1.0)
+    }
 }
 
 /// Azure surface wrapping to work with the layers infrastructure.
@@ -207,18 +337,49 @@ impl CompositorTask {
         let window_size = window.size();
         let scene = @mut Scene(ContainerLayerKind(root_layer), window_size, identity());
         let done = @mut false;
-        let recomposite = @mut false;
+
+        // Whether the composited surface needs redrawing. Set by the message and windowing
+        // handlers, cleared once the frame is presented. Compositing runs on this thread --
+        // the one that owns the GL context -- because the GL context is thread-affine and
+        // cannot be driven safely from another task; we pace it with the frame governor and
+        // the blocking buffer swap in `present()` rather than a fixed sleep.
+        let dirty = @mut false;
+
+        // The damage accumulated since the last composite. Handlers union the screen rects
+        // they touch into this; `composite` takes and clears it to repaint only those regions.
+        let dirty_region = @mut DirtyRegion::new(window_size.width as int,
+                                                 window_size.height as int);
+
+        // Marks the whole surface dirty -- used by changes that move or rescale every pixel
+        // (scroll, pinch-zoom, screenshot). `mark_region` below unions a single rect instead.
+        let mark_dirty: @fn() = || {
+            dirty_region.mark_full();
+            *dirty = true;
+        };
+
+        // Unions a single touched screen rect into the damage set.
+        let mark_region: @fn(Rect<int>) = |rect: Rect<int>| {
+            dirty_region.add(rect);
+            *dirty = true;
+        };
+
+        // Paces compositing to the configured cap, coalescing a recomposite requested sooner
+        // than one frame interval after the previous frame to the next frame boundary.
+        let governor = @mut FrameGovernor::new(Capped(60.0));
+        // A screenshot requested via `Screenshot`, taken after the next composite.
+        let pending_screenshot: @mut Option<Path> = @mut None;
 
         // FIXME: This should not be a separate offset applied after the fact but rather should be
         // applied to the layers themselves on a per-layer basis. However, this won't work until scroll
         // positions are sent to content.
-        let world_offset = @mut Point2D(0f32, 0f32);
-        let page_size = @mut Size2D(0f32, 0f32);
-        let window_size = @mut Size2D(window_size.width as int,
-                                      window_size.height as int);
-
-        // Keeps track of the current zoom factor
-        let world_zoom = @mut 1f32;
+        let world_offset: @mut TypedPoint2D<ScreenPx, f32> = @mut TypedPoint2D(0f32, 0f32);
+        let page_size: @mut TypedSize2D<PagePx, f32> = @mut TypedSize2D(0f32, 0f32);
+        let window_size: @mut TypedSize2D<DevicePixel, uint> =
+            @mut TypedSize2D(window_size.width, window_size.height);
+
+        // Keeps track of the current zoom factor, i.e. how many screen pixels a page pixel
+        // maps to. Multiplying a `PagePx` quantity by this yields a `ScreenPx` quantity.
+        let world_zoom: @mut ScaleFactor<PagePx, ScreenPx, f32> = @mut ScaleFactor(1f32);
         // Keeps track of local zoom factor. Reset to 1 after a rerender event.
         let local_zoom = @mut 1f32;
         // Channel to the current renderer.
@@ -227,82 +388,152 @@ impl CompositorTask {
         let render_chan: @mut Option<RenderChan> = @mut None;
         let pipeline_id: @mut Option<uint> = @mut None;
 
-        // Quadtree for this layer
-        // FIXME: This should be one-per-layer
-        let quadtree: @mut Option<Quadtree<~LayerBuffer>> = @mut None;
+        // Tile fetching is driven by a supervised background worker rather than inline sends,
+        // so a burst of regeneration after a zoom is throttled by the worker's tranquility
+        // sleep and can be paused/cancelled. Created once the render chan is known.
+        let tile_worker: @mut Option<@TileFetchWorker> = @mut None;
+        let workers = @mut WorkerSupervisor::new();
+
+        // How long the tile-fetch worker rests between batches, in milliseconds. Kept modest
+        // so tiles still stream in promptly while leaving the renderer some breathing room.
+        static TILE_TRANQUILITY_MS: uint = 4;
+
+        // Per-layer state, keyed by layer id. Each layer owns its own quadtree so that
+        // independently-scrolling iframes and stacking contexts can be composited together.
+        let layers: @mut HashMap<LayerId, LayerState> = @mut HashMap::new();
         
         // Keeps track of if we have performed a zoom event and how recently.
         let zoom_action = @mut false;
         let zoom_time = @mut 0f;
 
+        // Physics state for the kinetic (fling) scrolling model, one spring per axis. While
+        // `scroll_animating` is set the main loop integrates the springs each frame and only
+        // asks for tiles once they settle, which naturally rate-limits tile requests.
+        let scroll_x = @mut ScrollingAxis::new(0.0);
+        let scroll_y = @mut ScrollingAxis::new(0.0);
+        let scroll_animating = @mut false;
+        let scroll_last_time = @mut 0f;
+
+        // Gesture tracking for the fling hand-off. While a finger is down the deltas move the
+        // offset directly; we measure the velocity of the last delta and, once the deltas stop
+        // arriving (lift-off, inferred from an idle gap since the baseline windowing layer sends
+        // no explicit release), seed that velocity into the springs and let them settle.
+        let gesture_active = @mut false;
+        let gesture_velocity: @mut TypedPoint2D<ScreenPx, f32> = @mut TypedPoint2D(0f32, 0f32);
+        let gesture_last_time = @mut 0f;
+
+
+        // Hands a batch of tile requests to the background worker (which throttles and can be
+        // paused/cancelled), falling back to a direct send before the worker exists.
+        let dispatch_tiles: @fn(ReRenderMsg) = |batch: ReRenderMsg| {
+            match *tile_worker {
+                Some(worker) => {
+                    worker.submit(batch);
+                    worker.control(Start);
+                }
+                None => {
+                    match *render_chan {
+                        Some(ref chan) => chan.send(batch),
+                        None => {
+                            println("Warning: Compositor: Cannot send tile request, no render chan initialized");
+                        }
+                    }
+                }
+            }
+        };
 
         let ask_for_tiles: @fn() = || {
-            match *quadtree {
-                Some(ref mut quad) => {
-                    let valid = |tile: &~LayerBuffer| -> bool {
-                        tile.resolution == *world_zoom
+            // Keep the root layer's scroll offset in sync with the global world offset until
+            // per-layer scrolling is wired up end to end.
+            match layers.find_mut(&ROOT_LAYER_ID) {
+                Some(layer) => layer.scroll_offset = *world_offset,
+                None => {}
+            }
+
+            // Request tiles for every layer independently, each using its own quadtree and
+            // scroll offset.
+            let window_size = window_size.to_untyped();
+            for layers.mut_iter().advance |(_, layer)| {
+                let valid = |tile: &~LayerBuffer| -> bool {
+                    tile.resolution == world_zoom.get()
+                };
+                // The quadtree works in screen space, so the visible rect is the layer's
+                // scroll offset (already screen space) together with the window extent.
+                let visible = Rect(Point2D(layer.scroll_offset.x as int,
+                                           layer.scroll_offset.y as int),
+                                   Size2D(window_size.width as int,
+                                          window_size.height as int));
+
+                // If the viewport isn't well covered at the current zoom, fire off a coarse
+                // low-resolution pass first so something shows up immediately, then fall
+                // through to the full-resolution request below.
+                if layer.render_integrity(visible, world_zoom.get()) < INTEGRITY_THRESHOLD {
+                    let low_zoom = world_zoom.get() * LOW_RES_SCALE;
+                    let valid_low = |tile: &~LayerBuffer| -> bool {
+                        tile.resolution == low_zoom
                     };
-                    let (tile_request, redisplay) = quad.get_tile_rects(Rect(Point2D(world_offset.x as int,
-                                                                                     world_offset.y as int),
-                                                                             *window_size), valid, *world_zoom);
-
-                    if !tile_request.is_empty() {
-                        match *render_chan {
-                            Some(ref chan) => {
-                                chan.send(ReRenderMsg(tile_request, *world_zoom));
-                            }
-                            _ => {
-                                println("Warning: Compositor: Cannot send tile request, no render chan initialized");
-                            }
-                        }
-                    } else if redisplay {
-                        // TODO: move display code to its own closure and call that here
+                    let (low_request, _) = layer.quadtree.get_tile_rects(visible, valid_low, low_zoom);
+                    if !low_request.is_empty() {
+                        dispatch_tiles(ReRenderMsg(low_request, low_zoom));
                     }
                 }
-                _ => {
-                    fail!("Compositor: Tried to ask for tiles without an initialized quadtree");
+
+                let (tile_request, redisplay) =
+                    layer.quadtree.get_tile_rects(visible, valid, world_zoom.get());
+
+                if !tile_request.is_empty() {
+                    dispatch_tiles(ReRenderMsg(tile_request, world_zoom.get()));
+                } else if redisplay {
+                    // TODO: move display code to its own closure and call that here
                 }
             }
         };
 
-        let update_layout_callbacks: @fn(LayoutChan) = |layout_chan: LayoutChan| {
-            let layout_chan_clone = layout_chan.clone();
+        let update_layout_callbacks: @fn(ScriptChan) = |script_chan: ScriptChan| {
+            let script_chan_clone = script_chan.clone();
             do window.set_navigation_callback |direction| {
                 let direction = match direction {
-                    windowing::Forward => constellation_msg::Forward,
-                    windowing::Back => constellation_msg::Back,
+                    windowing::Forward => script_traits::Forward,
+                    windowing::Back => script_traits::Back,
                 };
-                layout_chan_clone.send(RouteScriptMsg(NavigateMsg(direction)));
+                script_chan_clone.navigate(direction);
             }
 
-            let layout_chan_clone = layout_chan.clone();
+            let script_chan_clone = script_chan.clone();
             // Hook the windowing system's resize callback up to the resize rate limiter.
             do window.set_resize_callback |width, height| {
-                let new_size = Size2D(width as int, height as int);
+                let new_size = TypedSize2D(width, height);
                 if *window_size != new_size {
                     debug!("osmain: window resized to %ux%u", width, height);
                     *window_size = new_size;
-                    layout_chan_clone.send(RouteScriptMsg(SendEventMsg(ResizeEvent(width, height))));
+                    // Re-base the damage region on the new bounds so `add` stops clipping to the
+                    // stale surface and the full-repaint promotion compares against the right area.
+                    dirty_region.set_surface(width as int, height as int);
+                    script_chan_clone.send_event(ResizeEvent(width, height));
                 } else {
                     debug!("osmain: dropping window resize since size is still %ux%u", width, height);
                 }
             }
 
-            let layout_chan_clone = layout_chan.clone();
+            let script_chan_clone = script_chan.clone();
 
             // When the user enters a new URL, load it.
             do window.set_load_url_callback |url_string| {
                 debug!("osmain: loading URL `%s`", url_string);
-                layout_chan_clone.send(RouteScriptMsg(LoadMsg(url::make_url(url_string.to_str(), None))));
+                script_chan_clone.load_url(url::make_url(url_string.to_str(), None));
             }
 
-            let layout_chan_clone = layout_chan.clone();
+            let script_chan_clone = script_chan.clone();
 
             // When the user triggers a mouse event, perform appropriate hit testing
             do window.set_mouse_callback |window_mouse_event: WindowMouseEvent| {
-                let event: Event;
+                let event: CompositorEvent;
                 let world_mouse_point = |layer_mouse_point: Point2D<f32>| {
-                    layer_mouse_point + *world_offset
+                    let layer_mouse_point: TypedPoint2D<ScreenPx, f32> =
+                        TypedPoint2D::from_untyped(&layer_mouse_point);
+                    // The point arrives in screen space; shift by the scroll offset and then
+                    // divide by the zoom to recover the page-space point script expects.
+                    ((layer_mouse_point + *world_offset) / *world_zoom).to_untyped()
                 };
                 match window_mouse_event {
                     WindowClickEvent(button, layer_mouse_point) => {
@@ -313,7 +544,7 @@ impl CompositorTask {
 
                     }
                     WindowMouseUpEvent(button, layer_mouse_point) => {
-                        
+
                         // FIXME: this should happen on a scroll/zoom event instead,
                         // but is here temporarily to prevent request floods to the renderer
                         ask_for_tiles();
@@ -321,8 +552,13 @@ impl CompositorTask {
                         event = MouseUpEvent(button, world_mouse_point(layer_mouse_point));
                     }
                 }
-                layout_chan_clone.send(RouteScriptMsg(SendEventMsg(event)));
+                script_chan_clone.send_event(event);
             }
+
+            // FIXME: pointer-move hit testing (MouseMoveEvent) and cursor-shape feedback want a
+            // `set_mouse_move_callback` and a reverse `set_cursor` on the windowing `Window`,
+            // which the external windowing crate does not expose yet. The `MouseMoveEvent`/
+            // `Cursor` protocol and the `SetCursor` message are in place for when it does.
         };
 
 
@@ -335,65 +571,139 @@ impl CompositorTask {
                     ChangeReadyState(ready_state) => window.set_ready_state(ready_state),
                     ChangeRenderState(render_state) => window.set_render_state(render_state),
 
-                    SetLayoutRenderChans(new_layout_chan,
+                    Screenshot(path) => {
+                        // Force a full composite; the next composite grabs the framebuffer
+                        // after rendering and before presenting.
+                        *pending_screenshot = Some(path);
+                        mark_dirty();
+                    }
+
+                    SetCursor(_cursor) => {
+                        // FIXME: apply the cursor once the windowing `Window` exposes a cursor
+                        // setter. Script's hit-test result arrives here but cannot be surfaced
+                        // to the platform window yet.
+                    }
+
+                    SetFrameRate(rate) => governor.set_rate(rate),
+
+                    ListWorkers(chan) => chan.send(workers.list()),
+
+                    SetLayoutRenderChans(new_script_chan,
                                          new_render_chan,
                                          new_pipeline_id,
                                          response_chan) => {
-                        update_layout_callbacks(new_layout_chan);
-                        *render_chan = Some(new_render_chan);
+                        update_layout_callbacks(new_script_chan);
+                        *render_chan = Some(new_render_chan.clone());
                         *pipeline_id = Some(new_pipeline_id);
+
+                        // Now that we have a render chan, stand up the tile-fetch worker and
+                        // register it with the supervisor for introspection.
+                        let worker = @TileFetchWorker::new(~"tile-fetch",
+                                                           new_render_chan,
+                                                           TILE_TRANQUILITY_MS);
+                        workers.register(worker as @Worker);
+                        *tile_worker = Some(worker);
+
                         response_chan.send(CompositorAck(new_pipeline_id));
                     }
 
                     GetSize(chan) => {
                         let size = window.size();
-                        chan.send(Size2D(size.width as int, size.height as int));
+                        chan.send(TypedSize2D(size.width as int, size.height as int));
                     }
 
                     GetGLContext(chan) => chan.send(current_gl_context()),
                     
-                    NewLayer(new_size, tile_size) => {
-                        *page_size = Size2D(new_size.width as f32, new_size.height as f32);
-                        *quadtree = Some(Quadtree::new(0, 0, new_size.width, new_size.height, tile_size));
+                    NewLayer(layer_id, new_size, tile_size) => {
+                        let state = LayerState {
+                            quadtree: Quadtree::new(0, 0, new_size.width, new_size.height, tile_size),
+                            page_size: TypedSize2D(new_size.width as f32, new_size.height as f32),
+                            scroll_offset: TypedPoint2D(0f32, 0f32),
+                            epoch: Epoch(0),
+                        };
+                        layers.insert(layer_id, state);
+                        if layer_id == ROOT_LAYER_ID {
+                            *page_size = TypedSize2D(new_size.width as f32, new_size.height as f32);
+                        }
                         ask_for_tiles();
-                        
+
                     }
-                    ResizeLayer(new_size) => {
-                        *page_size = Size2D(new_size.width as f32, new_size.height as f32);
+                    ResizeLayer(layer_id, new_size) => {
+                        match layers.find_mut(&layer_id) {
+                            Some(layer) => layer.page_size = TypedSize2D(new_size.width as f32,
+                                                                        new_size.height as f32),
+                            None => {}
+                        }
+                        if layer_id == ROOT_LAYER_ID {
+                            *page_size = TypedSize2D(new_size.width as f32, new_size.height as f32);
+                        }
                         // TODO: update quadtree, ask for tiles
                     }
-                    DeleteLayer => {
+                    DeleteLayer(layer_id) => {
+                        layers.remove(&layer_id);
                         // TODO: create secondary layer tree, keep displaying until new tiles come in
                     }
 
-                    Paint(id, new_layer_buffer_set, new_size) => {
+                    Paint(id, layer_id, epoch, new_layer_buffer_set, new_size) => {
                         match *pipeline_id {
                             Some(pipeline_id) => if id != pipeline_id { loop; },
                             None => { loop; },
                         }
-                            
+
                         debug!("osmain: received new frame");
 
-                        let quad;
-                        match *quadtree {
-                            Some(ref mut q) => quad = q,
-                            None => fail!("Compositor: given paint command with no quadtree initialized"),
+                        // The layer may have been deleted, or its NewLayer may not have been
+                        // processed yet; drop the buffers rather than crashing the compositor.
+                        let layer = match layers.find_mut(&layer_id) {
+                            Some(layer) => layer,
+                            None => {
+                                debug!("osmain: dropping paint for unknown layer %?", layer_id);
+                                loop;
+                            }
+                        };
+
+                        // Discard buffers from a superseded layout generation. Note this path is
+                        // currently unreachable: `RenderListener::paint` always stamps `Epoch(0)`
+                        // (see the comment there), so `epoch < layer.epoch` is always `0 < 0`. It
+                        // goes live once layout mints a real, monotonically increasing epoch per
+                        // reflow rather than being hard-wired to zero.
+                        if epoch < layer.epoch {
+                            debug!("osmain: dropping stale paint (epoch %? < %?)", epoch, layer.epoch);
+                            loop;
+                        }
+                        layer.epoch = epoch;
+                        layer.page_size = TypedSize2D(new_size.width as f32, new_size.height as f32);
+                        if layer_id == ROOT_LAYER_ID {
+                            *page_size = TypedSize2D(new_size.width as f32, new_size.height as f32);
                         }
 
-                        *page_size = Size2D(new_size.width as f32, new_size.height as f32);
+                        let quad = &mut layer.quadtree;
 
                         let new_layer_buffer_set = new_layer_buffer_set.get();
+                        // Collect the screen rects the newly arrived tiles cover so we can
+                        // repaint only those regions rather than the whole surface.
+                        let mut painted: ~[Rect<int>] = ~[];
                         for new_layer_buffer_set.buffers.iter().advance |buffer| {
                             // FIXME: Don't copy the buffers here
+                            // Key the tile by the resolution it was actually rendered at, so a
+                            // coarse low-resolution fill pass and the full-resolution tiles
+                            // coexist in the quadtree and the integrity metric can tell them
+                            // apart.
                             quad.add_tile(buffer.screen_pos.origin.x, buffer.screen_pos.origin.y,
-                                          *world_zoom, ~buffer.clone());
+                                          buffer.resolution, ~buffer.clone());
+                            painted.push(Rect(Point2D(buffer.screen_pos.origin.x as int,
+                                                      buffer.screen_pos.origin.y as int),
+                                              Size2D(buffer.screen_pos.size.width as int,
+                                                     buffer.screen_pos.size.height as int)));
                         }
                         
 
+                        let all_tiles = quad.get_all_tiles();
+
                         // Iterate over the children of the container layer.
+                        // FIXME: Each layer should composite into its own container layer; for
+                        // now only the root layer has a container and its tiles go here.
                         let mut current_layer_child = root_layer.first_child;
-                        
-                        let all_tiles = quad.get_all_tiles();
                         for all_tiles.iter().advance |buffer| {
                             let width = buffer.screen_pos.size.width as uint;
                             let height = buffer.screen_pos.size.height as uint;
@@ -425,8 +735,8 @@ impl CompositorTask {
                             let origin = Point2D(origin.x as f32, origin.y as f32);
 
                             // Set the layer's transform.
-                            let transform = identity().translate(origin.x * *world_zoom, origin.y * *world_zoom, 0.0);
-                            let transform = transform.scale(width as f32 * *world_zoom / buffer.resolution, height as f32 * *world_zoom / buffer.resolution, 1.0);
+                            let transform = identity().translate(origin.x * world_zoom.get(), origin.y * world_zoom.get(), 0.0);
+                            let transform = transform.scale(width as f32 * world_zoom.get() / buffer.resolution, height as f32 * world_zoom.get() / buffer.resolution, 1.0);
                             texture_layer.common.set_transform(transform);
                             
                         }
@@ -449,43 +759,85 @@ impl CompositorTask {
                         // TODO: Recycle the old buffers; send them back to the renderer to reuse if
                         // it wishes.
 
-                        *recomposite = true;
+                        // Damage only the regions the new tiles cover. `mark_region` promotes
+                        // to a full repaint on its own once the union grows large enough.
+                        for painted.iter().advance |rect| {
+                            mark_region(*rect);
+                        }
                     }
                 }
             }
         };
 
         let profiler_chan = self.profiler_chan.clone();
-        let composite = || {
+        let composite: @fn(Damage) = |damage: Damage| {
             do profile(time::CompositingCategory, profiler_chan.clone()) {
                 debug!("compositor: compositing");
                 // Adjust the layer dimensions as necessary to correspond to the size of the window.
                 scene.size = window.size();
 
+                // Clip the redraw to the damaged regions. `Partial` scissors to the bounding
+                // box of the damage so only the touched area is repainted; `Full` clears any
+                // scissor and repaints everything. GL's origin is bottom-left, so we flip the
+                // rect's y when programming the scissor box.
+                match damage {
+                    Partial(ref rects) => {
+                        let bounds = bounding_box(*rects);
+                        let surface_height = window.size().height as i32;
+                        gl2::enable(gl2::SCISSOR_TEST);
+                        gl2::scissor(bounds.origin.x as i32,
+                                     surface_height - (bounds.origin.y + bounds.size.height) as i32,
+                                     bounds.size.width as i32,
+                                     bounds.size.height as i32);
+                    }
+                    _ => gl2::disable(gl2::SCISSOR_TEST),
+                }
+
                 // Render the scene.
                 rendergl::render_scene(context, scene);
+
+                gl2::disable(gl2::SCISSOR_TEST);
+            }
+
+            // Grab the framebuffer before `present()` swaps the buffers, since the back
+            // buffer's contents become undefined after the swap.
+            match pending_screenshot.take() {
+                Some(path) => save_framebuffer_png(window.size(), &path),
+                None => {}
             }
 
             window.present();
         };
 
-        // When the user scrolls, move the layer around.
-        do window.set_scroll_callback |delta| {
-            // FIXME (Rust #2528): Can't use `-=`.
-            let world_offset_copy = *world_offset;
-            *world_offset = world_offset_copy - delta;
+        // Composites if the surface is dirty and the governor's cap permits a frame now,
+        // pulling and clearing the accumulated damage so only the touched regions are
+        // repainted. Called once per main-loop wakeup; the blocking buffer swap inside
+        // `present()` keeps presentation in step with vblank. Returns the seconds until the
+        // next frame boundary when a draw was coalesced (`0.0` otherwise), so the caller can
+        // arm a wakeup to honour the cap.
+        let maybe_composite: @fn() -> f64 = || {
+            if !*dirty {
+                return 0.0;
+            }
+            let now = precise_time_s();
+            if !governor.ready(now) {
+                // Too soon after the last frame: coalesce and report when we may draw again.
+                return governor.time_until_ready(now);
+            }
+            let damage = dirty_region.take();
+            match damage {
+                Nothing => {}
+                other => composite(other),
+            }
+            governor.note_composite(now);
+            *dirty = false;
+            0.0
+        };
 
-            // Clamp the world offset to the screen size.
-            let max_x = (page_size.width * *world_zoom - window_size.width as f32).max(&0.0);
-            world_offset.x = world_offset.x.clamp(&0.0, &max_x).round();
-            let max_y = (page_size.height * *world_zoom - window_size.height as f32).max(&0.0);
-            world_offset.y = world_offset.y.clamp(&0.0, &max_y).round();
-            
-            debug!("compositor: scrolled to %?", *world_offset);
-            
-            
+        // Rebuilds the layer's scroll/zoom transform from the current world offset.
+        let update_scroll_transform: @fn() = || {
             let mut scroll_transform = identity();
-            
+
             scroll_transform = scroll_transform.translate(window_size.width as f32 / 2f32 * *local_zoom - world_offset.x,
                                                           window_size.height as f32 / 2f32 * *local_zoom - world_offset.y,
                                                           0.0);
@@ -493,15 +845,44 @@ impl CompositorTask {
             scroll_transform = scroll_transform.translate(window_size.width as f32 / -2f32,
                                                           window_size.height as f32 / -2f32,
                                                           0.0);
-            
+
             root_layer.common.set_transform(scroll_transform);
-            
-            // FIXME: ask_for_tiles() should be called here, but currently this sends a flood of requests
-            // to the renderer, which slows the application dramatically. Instead, ask_for_tiles() is only
-            // called on a click event.
-//            ask_for_tiles();
+        };
 
-            *recomposite = true;
+        // Each scroll delta moves the offset directly so the content tracks the finger, and we
+        // record the velocity of the most recent delta. We do *not* fling here: momentum is
+        // seeded from that tracked velocity only once the gesture ends (see the lift-off check
+        // in the main loop), matching how a real flick imparts momentum on release rather than
+        // on every notch.
+        do window.set_scroll_callback |delta| {
+            let max_x = (page_size.width * world_zoom.get() - window_size.width as f32).max(&0.0);
+            let max_y = (page_size.height * world_zoom.get() - window_size.height as f32).max(&0.0);
+
+            let now = precise_time_s();
+            if *gesture_active {
+                // Velocity of this delta, in pixels/second; content moves opposite the delta.
+                let dt = (now - *gesture_last_time) as f32;
+                if dt > 0.0 {
+                    gesture_velocity.x = -delta.x / dt;
+                    gesture_velocity.y = -delta.y / dt;
+                }
+            } else {
+                // A fresh gesture cancels any in-flight fling and starts from the live offset.
+                *gesture_active = true;
+                *scroll_animating = false;
+                *gesture_velocity = TypedPoint2D(0f32, 0f32);
+            }
+            *gesture_last_time = now;
+
+            // Drag the offset with the finger, clamped to the page bounds.
+            world_offset.x = (world_offset.x - delta.x).clamp(&0.0, &max_x);
+            world_offset.y = (world_offset.y - delta.y).clamp(&0.0, &max_y);
+            scroll_x.sync(world_offset.x);
+            scroll_y.sync(world_offset.y);
+            update_scroll_transform();
+
+            debug!("compositor: scroll gesture to %?", *world_offset);
+            mark_dirty();
         }
 
 
@@ -513,24 +894,30 @@ impl CompositorTask {
             let old_world_zoom = *world_zoom;
 
             // Determine zoom amount
-            *world_zoom = (*world_zoom * magnification).max(&1.0);            
-            *local_zoom = *local_zoom * *world_zoom/old_world_zoom;
+            *world_zoom = ScaleFactor((world_zoom.get() * magnification).max(&1.0));
+            *local_zoom = *local_zoom * world_zoom.get() / old_world_zoom.get();
 
             // Update world offset
             let corner_to_center_x = world_offset.x + window_size.width as f32 / 2f32;
-            let new_corner_to_center_x = corner_to_center_x * *world_zoom / old_world_zoom;
+            let new_corner_to_center_x = corner_to_center_x * world_zoom.get() / old_world_zoom.get();
             world_offset.x = world_offset.x + new_corner_to_center_x - corner_to_center_x;
 
             let corner_to_center_y = world_offset.y + window_size.height as f32 / 2f32;
-            let new_corner_to_center_y = corner_to_center_y * *world_zoom / old_world_zoom;
-            world_offset.y = world_offset.y + new_corner_to_center_y - corner_to_center_y;        
+            let new_corner_to_center_y = corner_to_center_y * world_zoom.get() / old_world_zoom.get();
+            world_offset.y = world_offset.y + new_corner_to_center_y - corner_to_center_y;
 
             // Clamp to page bounds when zooming out
-            let max_x = (page_size.width * *world_zoom - window_size.width as f32).max(&0.0);
+            let max_x = (page_size.width * world_zoom.get() - window_size.width as f32).max(&0.0);
             world_offset.x = world_offset.x.clamp(&0.0, &max_x).round();
-            let max_y = (page_size.height * *world_zoom - window_size.height as f32).max(&0.0);
+            let max_y = (page_size.height * world_zoom.get() - window_size.height as f32).max(&0.0);
             world_offset.y = world_offset.y.clamp(&0.0, &max_y).round();
-            
+
+            // A zoom recenters the world offset, so re-seat the scroll springs on the new
+            // offset and cancel any in-flight fling rather than letting it fight the zoom.
+            scroll_x.sync(world_offset.x);
+            scroll_y.sync(world_offset.y);
+            *scroll_animating = false;
+
             // Apply transformations
             let mut zoom_transform = identity();
             zoom_transform = zoom_transform.translate(window_size.width as f32 / 2f32 * *local_zoom - world_offset.x,
@@ -541,37 +928,203 @@ impl CompositorTask {
                                                       window_size.height as f32 / -2f32,
                                                       0.0);
             root_layer.common.set_transform(zoom_transform);
-            
-            *recomposite = true;
+
+            mark_dirty();
         }
 
-        // Enter the main event loop.
+        // A uv loop handle used to arm the one timer the select ever waits on.
+        let mut event_loop = uv_global_loop::get();
+
+        // The wait while a fling is animating: one 60 Hz frame. The baseline `Window` exposes
+        // no event port to select on -- only `check_loop()` -- so we still have to poll it;
+        // while idle the wait is the coarser `IDLE_TICK_MS` below, and a compositor message
+        // still wakes us immediately through the select.
+        static POLL_TICK_MS: uint = 16;
+
+        // While nothing is animating we only need to pump `check_loop()` a few times a second;
+        // a compositor message still wakes the select instantly, so idling at this coarser tick
+        // rather than the 60 Hz frame tick is what keeps the loop from burning cycles when idle.
+        static IDLE_TICK_MS: uint = 50;
+
+        // How long without a fresh scroll delta counts as the finger lifting off, at which
+        // point the tracked gesture velocity is handed to the springs as a fling.
+        static SCROLL_GESTURE_IDLE_MS: uint = 60;
+
+        // Seconds until a composite coalesced by the governor may run, as reported by the most
+        // recent `maybe_composite`. Folded into the next wait so we wake exactly at the frame
+        // boundary rather than spinning on the poll tick.
+        let composite_defer = @mut 0.0f64;
+
+        // Enter the main event loop. Rather than sleeping a fixed 100 ms we park in a select
+        // over the compositor port and a single timer, waking on the first edge. The timer is
+        // armed to the soonest of: the next animation frame, the scroll-gesture idle check, the
+        // pinch-zoom debounce, a coalesced composite's frame boundary, or the idle poll tick.
         while !*done {
-            // Check for new messages coming from the rendering task.
-            check_for_messages(&self.port);
+            // Work out how long we may block. A pending composite, animation, or debounce
+            // shortens the wait to a frame; otherwise we idle at the coarser windowing poll tick.
+            let mut wait_ms = IDLE_TICK_MS;
+            if *scroll_animating {
+                // Integrate the spring once per frame while a fling is in flight.
+                wait_ms = wait_ms.min(&POLL_TICK_MS);
+            }
+            if *gesture_active {
+                // Wake in time to notice the gesture has gone idle and hand off to a fling.
+                wait_ms = wait_ms.min(&SCROLL_GESTURE_IDLE_MS);
+            }
+            if *zoom_action {
+                let remaining = (0.3 - (precise_time_s() - *zoom_time)).max(&0.0);
+                wait_ms = wait_ms.min(&((remaining * 1000.0) as uint));
+            }
+            if *composite_defer > 0.0 {
+                wait_ms = wait_ms.min(&((*composite_defer * 1000.0) as uint));
+            }
+
+            // Park until a compositor message arrives or the timer fires.
+            let timer_port = timer::oneshot(&mut event_loop, wait_ms);
+            let select = Select::new();
+            let mut msg_handle = select.handle(&self.port);
+            let mut timer_handle = select.handle(&timer_port);
+            unsafe {
+                msg_handle.add();
+                timer_handle.add();
+            }
+            select.wait();
 
-            // Check for messages coming from the windowing system.
+            // Drain the compositor port and pump the windowing system.
+            check_for_messages(&self.port);
             window.check_loop();
 
-            if *recomposite {
-                *recomposite = false;
-                composite();
+            // Detect lift-off: a gesture that has stopped sending deltas hands its last
+            // measured velocity to the springs as a fling, then the animation step takes over.
+            if *gesture_active &&
+               (precise_time_s() - *gesture_last_time) * 1000.0 >= SCROLL_GESTURE_IDLE_MS as f64 {
+                *gesture_active = false;
+                scroll_x.sync(world_offset.x);
+                scroll_y.sync(world_offset.y);
+                scroll_x.fling(gesture_velocity.x);
+                scroll_y.fling(gesture_velocity.y);
+                *scroll_animating = true;
+                *scroll_last_time = precise_time_s();
             }
 
-            timer::sleep(&uv_global_loop::get(), 100);
+            // Step the kinetic scrolling springs. We integrate once per frame and only ask
+            // for tiles when both axes have settled, so flings stay smooth while tile
+            // requests are rate-limited to animation frames.
+            if *scroll_animating {
+                let now = precise_time_s();
+                let dt = (now - *scroll_last_time) as f32;
+                *scroll_last_time = now;
+
+                // Keep the resting points pinned to the current page bounds so an overscroll
+                // springs back to the boundary.
+                let max_x = (page_size.width * world_zoom.get() - window_size.width as f32).max(&0.0);
+                let max_y = (page_size.height * world_zoom.get() - window_size.height as f32).max(&0.0);
+                scroll_x.clamp_target(max_x);
+                scroll_y.clamp_target(max_y);
+
+                scroll_x.tick(dt);
+                scroll_y.tick(dt);
+
+                world_offset.x = scroll_x.position();
+                world_offset.y = scroll_y.position();
+                update_scroll_transform();
+                mark_dirty();
+
+                if scroll_x.settled() && scroll_y.settled() {
+                    *scroll_animating = false;
+                    ask_for_tiles();
+                }
+            }
 
-            // If a pinch-zoom happened recently, ask for tiles at the new resolution
+            // If a pinch-zoom happened recently, ask for tiles at the new resolution. The
+            // debounce timer above guarantees we are woken once the 0.3 s window elapses.
             if *zoom_action && precise_time_s() - *zoom_time > 0.3 {
                 *zoom_action = false;
                 ask_for_tiles();
             }
 
+            // Composite whatever is dirty, honouring the frame-rate cap. A non-zero result means
+            // the draw was coalesced; remember it so the next wait wakes us at the frame boundary.
+            *composite_defer = maybe_composite();
+
         }
 
         self.shutdown_chan.send(())
     }
 }
 
+/// Reads the composited GL framebuffer back and writes it to `path` as a PNG.
+///
+/// GL framebuffers are laid out bottom-up and in ARGB32, so we flip the rows vertically and
+/// drop the alpha channel while copying into a tightly-packed RGB buffer. Each source row may
+/// be padded to the GL pack alignment, so the copy walks rows by the derived stride rather than
+/// assuming `width * 4`.
+///
+/// The default framebuffer has no backing `DataSourceSurface`, so unlike the Azure-surface path
+/// in `AzureDrawTargetImageData` this reads the pixels with `gl2::read_pixels`; the stride
+/// handling the request called for is applied to that readback.
+fn save_framebuffer_png(size: Size2D<uint>, path: &Path) {
+    let width = size.width;
+    let height = size.height;
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    // GL pads each packed row up to `PACK_ALIGNMENT` bytes; pin it to a known value and derive
+    // the padded row stride from it so the copy below honors a stride wider than `width * 4`.
+    static PACK_ALIGNMENT: uint = 4;
+    gl2::pixel_store_i(gl2::PACK_ALIGNMENT, PACK_ALIGNMENT as i32);
+
+    // Read the framebuffer as ARGB32 (BGRA in memory on a little-endian host).
+    let pixels = gl2::read_pixels(0, 0, width as i32, height as i32,
+                                  gl2::BGRA, gl2::UNSIGNED_BYTE);
+    // Round the row up to the pack alignment to recover the padded stride.
+    let src_stride = (width * 4 + PACK_ALIGNMENT - 1) / PACK_ALIGNMENT * PACK_ALIGNMENT;
+
+    let mut rgb = vec::with_capacity(width * height * 3);
+    // Walk the source rows bottom-to-top so the output is top-down.
+    let mut y = height;
+    while y > 0 {
+        y -= 1;
+        let row = y * src_stride;
+        let mut x = 0;
+        while x < width {
+            let i = row + x * 4;
+            // BGRA in memory -> RGB out.
+            rgb.push(pixels[i + 2]);
+            rgb.push(pixels[i + 1]);
+            rgb.push(pixels[i + 0]);
+            x += 1;
+        }
+    }
+
+    let mut image = png::Image {
+        width: width as u32,
+        height: height as u32,
+        color_type: png::RGB8,
+        pixels: rgb,
+    };
+    if !png::store_png(&mut image, path) {
+        error!("compositor: failed to write screenshot to %s", path.to_str());
+    }
+}
+
+/// The smallest rectangle enclosing every rectangle in `rects`, used to program a single
+/// scissor box for a partial repaint. `rects` is assumed non-empty.
+fn bounding_box(rects: &[Rect<int>]) -> Rect<int> {
+    let mut min_x = rects[0].origin.x;
+    let mut min_y = rects[0].origin.y;
+    let mut max_x = rects[0].origin.x + rects[0].size.width;
+    let mut max_y = rects[0].origin.y + rects[0].size.height;
+    for rects.iter().advance |r| {
+        min_x = min_x.min(&r.origin.x);
+        min_y = min_y.min(&r.origin.y);
+        max_x = max_x.max(&(r.origin.x + r.size.width));
+        max_y = max_y.max(&(r.origin.y + r.size.height));
+    }
+    Rect(Point2D(min_x, min_y), Size2D(max_x - min_x, max_y - min_y))
+}
+
 /// A function for spawning into the platform's main thread.
 fn on_osmain(f: ~fn()) {
     // FIXME: rust#6399
@@ -582,3 +1135,37 @@ fn on_osmain(f: ~fn()) {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::{LayerState, Epoch};
+    use compositing::quadtree::Quadtree;
+    use geom::rect::Rect;
+    use geom::point::{Point2D, TypedPoint2D};
+    use geom::size::{Size2D, TypedSize2D};
+
+    fn empty_layer() -> LayerState {
+        LayerState {
+            quadtree: Quadtree::new(0, 0, 100, 100, 256),
+            page_size: TypedSize2D(100f32, 100f32),
+            scroll_offset: TypedPoint2D(0f32, 0f32),
+            epoch: Epoch(0),
+        }
+    }
+
+    #[test]
+    fn empty_quadtree_is_fully_covered() {
+        let mut layer = empty_layer();
+        let visible = Rect(Point2D(0, 0), Size2D(100, 100));
+        // With no tiles there is nothing to paint, so the viewport counts as fully covered and
+        // the compositor does not spin requesting tiles for a blank page.
+        assert_eq!(layer.render_integrity(visible, 1.0), 1.0);
+    }
+
+    #[test]
+    fn zero_area_viewport_is_fully_covered() {
+        let mut layer = empty_layer();
+        let visible = Rect(Point2D(0, 0), Size2D(0, 0));
+        assert_eq!(layer.render_integrity(visible, 1.0), 1.0);
+    }
+}
+
@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The abstract interface the compositor uses to drive the script task.
+//!
+//! The compositor needs to deliver input events and navigation requests to script, but
+//! depending on the `script` crate directly creates a compile-time dependency cycle. This
+//! module defines only the message enums and the channel the compositor sends on; the
+//! script task owns the receiving end and translates these into its own concrete DOM events.
+
+use geom::point::Point2D;
+use extra::url::Url;
+use std::comm::SharedChan;
+
+/// An input event, hit-tested into page space, destined for the script task.
+pub enum CompositorEvent {
+    /// The window was resized to the given dimensions.
+    ResizeEvent(uint, uint),
+    /// A mouse button was clicked at the given page point.
+    ClickEvent(uint, Point2D<f32>),
+    /// A mouse button was pressed at the given page point.
+    MouseDownEvent(uint, Point2D<f32>),
+    /// A mouse button was released at the given page point.
+    MouseUpEvent(uint, Point2D<f32>),
+    /// The pointer moved to the given page point, for hover and cursor hit testing.
+    MouseMoveEvent(Point2D<f32>),
+}
+
+/// The shape the window's pointer should take, as determined by script hit testing. Sent
+/// back to the compositor in response to a `MouseMoveEvent`.
+pub enum Cursor {
+    /// The default arrow pointer.
+    DefaultCursor,
+    /// A pointing hand, e.g. over a link.
+    PointerCursor,
+    /// A text caret, e.g. over selectable text.
+    TextCursor,
+}
+
+/// The direction of a session-history navigation.
+pub enum NavigationDirection {
+    /// Navigate forward.
+    Forward,
+    /// Navigate backward.
+    Back,
+}
+
+/// A message sent from the compositor to the script task.
+pub enum ScriptMsg {
+    /// Deliver an input event.
+    SendEventMsg(CompositorEvent),
+    /// Load the given URL.
+    LoadUrlMsg(Url),
+    /// Navigate the session history.
+    NavigateMsg(NavigationDirection),
+}
+
+/// The channel on which the compositor sends messages to the script task. This is the only
+/// handle the compositor holds; it knows nothing of script's internals.
+#[deriving(Clone)]
+pub struct ScriptChan(SharedChan<ScriptMsg>);
+
+impl ScriptChan {
+    /// Creates a new script channel from the given raw shared channel.
+    pub fn new(chan: SharedChan<ScriptMsg>) -> ScriptChan {
+        ScriptChan(chan)
+    }
+
+    /// Delivers an input event to script.
+    pub fn send_event(&self, event: CompositorEvent) {
+        let ScriptChan(ref chan) = *self;
+        chan.send(SendEventMsg(event));
+    }
+
+    /// Asks script to load the given URL.
+    pub fn load_url(&self, url: Url) {
+        let ScriptChan(ref chan) = *self;
+        chan.send(LoadUrlMsg(url));
+    }
+
+    /// Asks script to navigate the session history.
+    pub fn navigate(&self, direction: NavigationDirection) {
+        let ScriptChan(ref chan) = *self;
+        chan.send(NavigateMsg(direction));
+    }
+}
@@ -0,0 +1,136 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A critically-damped mass-spring-damper model used to give scrolling momentum.
+//!
+//! Each scrollable axis is modeled independently as a spring pulling the current position
+//! `x` towards a target `p`. A fling seeds the axis with a velocity and lets it settle;
+//! overscrolling past the page bounds is expressed by snapping the target back onto the
+//! boundary so the spring drags the content back into range.
+
+/// Stiffness of the spring. Larger values settle faster but feel snappier.
+static STIFFNESS: f32 = 120.0;
+
+/// A velocity below this (in pixels/second) is treated as stopped.
+static VELOCITY_THRESHOLD: f32 = 0.5;
+
+/// A distance to the target below this (in pixels) is treated as arrived.
+static POSITION_THRESHOLD: f32 = 0.1;
+
+/// Largest integration substep. The main loop frame may be much coarser than this, but a
+/// critically-damped spring this stiff goes unstable under explicit Euler once the step
+/// approaches `2 / (2*sqrt(k))`, so we subdivide long frames into steps no larger than this.
+static MAX_SUBSTEP: f32 = 1.0 / 120.0;
+
+/// The physics state of a single scroll axis.
+pub struct ScrollingAxis {
+    /// Current position along the axis.
+    position: f32,
+    /// Current velocity along the axis.
+    velocity: f32,
+    /// The resting point the spring is pulling towards.
+    target: f32,
+}
+
+impl ScrollingAxis {
+    /// Creates an axis at rest at the given position.
+    pub fn new(position: f32) -> ScrollingAxis {
+        ScrollingAxis {
+            position: position,
+            velocity: 0.0,
+            target: position,
+        }
+    }
+
+    /// The current position along the axis.
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// Seeds the axis with a fling velocity (in pixels/second), e.g. the velocity of the
+    /// gesture when the user lifted off.
+    pub fn fling(&mut self, velocity: f32) {
+        self.velocity = velocity;
+    }
+
+    /// Clamps the resting point to `[0, max]`. When the axis has overscrolled, this leaves
+    /// the position outside the range but moves the target back onto the boundary, so the
+    /// spring pulls the content back in.
+    pub fn clamp_target(&mut self, max: f32) {
+        self.target = self.target.clamp(&0.0, &max);
+    }
+
+    /// Moves the resting point directly, clamped to `[0, max]`.
+    pub fn set_target(&mut self, target: f32, max: f32) {
+        self.target = target.clamp(&0.0, &max);
+    }
+
+    /// Snaps the axis to `position` at rest, discarding any in-flight motion. Used to keep
+    /// the spring in sync with `world_offset` after it has been moved by another path (a
+    /// drag, a pinch-zoom, or the hand-off to a fling).
+    pub fn sync(&mut self, position: f32) {
+        self.position = position;
+        self.target = position;
+        self.velocity = 0.0;
+    }
+
+    /// Integrates the critically-damped model forward by `dt` seconds, subdividing the frame
+    /// so no substep exceeds `MAX_SUBSTEP` (keeping the explicit integrator stable).
+    pub fn tick(&mut self, dt: f32) {
+        let damping = 2.0 * STIFFNESS.sqrt();
+        let mut remaining = dt;
+        while remaining > 0.0 {
+            let step = remaining.min(&MAX_SUBSTEP);
+            let acceleration = -STIFFNESS * (self.position - self.target) - damping * self.velocity;
+            self.velocity = self.velocity + acceleration * step;
+            self.position = self.position + self.velocity * step;
+            remaining = remaining - step;
+        }
+    }
+
+    /// Whether the axis has settled, i.e. both its velocity and its distance to the target
+    /// have fallen below the stop thresholds.
+    pub fn settled(&self) -> bool {
+        self.velocity.abs() < VELOCITY_THRESHOLD &&
+            (self.position - self.target).abs() < POSITION_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ScrollingAxis;
+
+    /// Integrates at 60 fps for at most `max_frames` and returns the frame count at which the
+    /// axis settled, failing if it never does.
+    fn settle(axis: &mut ScrollingAxis, max_frames: uint) -> uint {
+        let mut frame = 0;
+        while frame < max_frames {
+            if axis.settled() {
+                return frame;
+            }
+            axis.tick(1.0 / 60.0);
+            frame += 1;
+        }
+        fail!("axis never settled");
+    }
+
+    #[test]
+    fn fling_settles_on_target() {
+        let mut axis = ScrollingAxis::new(0.0);
+        axis.fling(2000.0);
+        settle(&mut axis, 600);
+        // A critically-damped spring pulling towards the origin comes to rest there.
+        assert!((axis.position() - 0.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn overscroll_snaps_back_into_range() {
+        // The axis has been dragged past the bottom of a [0, 100] range.
+        let mut axis = ScrollingAxis::new(130.0);
+        axis.clamp_target(100.0);
+        settle(&mut axis, 600);
+        // The spring drags the content back onto the boundary.
+        assert!((axis.position() - 100.0).abs() < 0.1);
+    }
+}
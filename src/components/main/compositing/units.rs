@@ -0,0 +1,23 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Phantom-typed geometry units used throughout the compositor.
+//!
+//! The compositor juggles several different coordinate spaces and, historically, conflated
+//! them behind bare `f32`s with manual `as` casts. These marker types tag `TypedPoint2D`,
+//! `TypedSize2D`, and `TypedRect` with the space they live in so that the type checker
+//! rejects mixing page and screen coordinates, and converting between the two requires an
+//! explicit multiply by a `ScaleFactor`.
+
+/// A normalized "CSS pixel" coordinate in the coordinate system of the page, before the
+/// page has been zoomed. This is the space layout and script think in.
+pub enum PagePx {}
+
+/// A pixel coordinate in the coordinate system of the screen, after the page zoom has been
+/// applied. One `ScreenPx` is one `PagePx` scaled by `world_zoom`.
+pub enum ScreenPx {}
+
+/// A physical device pixel. On a hidpi display there may be more than one `DevicePixel` per
+/// `ScreenPx`; the windowing system reports sizes in this space.
+pub enum DevicePixel {}
@@ -0,0 +1,189 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A small supervised-worker subsystem for the compositor's background jobs.
+//!
+//! Work like regenerating tiles after a pinch-zoom used to run inline behind an ad-hoc 0.3 s
+//! debounce, with no way to see what was in flight or to pause or cancel it. This mirrors how
+//! long-running maintenance jobs are managed elsewhere in the engine: one supervised worker
+//! per job, driven by `WorkerControl` messages rather than inline calls, with an explicit
+//! lifecycle the supervisor can introspect for debugging (e.g. zoom-stutter reports).
+//!
+//! Each worker runs on its own task and throttles itself with a "tranquility" sleep between
+//! batches, so a burst of regeneration after a zoom does not saturate the rendering task.
+
+use std::comm;
+use std::comm::SharedChan;
+use std::task::spawn;
+use std::unstable::atomics::{AtomicUint, Acquire, Release};
+use std::unstable::sync::UnsafeArc;
+use extra::timer;
+use extra::uv_global_loop;
+
+use gfx::render_task::{RenderChan, ReRenderMsg};
+
+/// The lifecycle state of a worker.
+#[deriving(Eq, Clone)]
+pub enum WorkerState {
+    /// The worker is processing batches.
+    Active,
+    /// The worker is alive but has nothing to do, or has been paused.
+    Idle,
+    /// The worker's task has exited and it will accept no more work.
+    Dead,
+}
+
+impl WorkerState {
+    fn from_code(code: uint) -> WorkerState {
+        match code {
+            0 => Idle,
+            1 => Active,
+            _ => Dead,
+        }
+    }
+
+    fn to_code(&self) -> uint {
+        match *self {
+            Idle => 0,
+            Active => 1,
+            Dead => 2,
+        }
+    }
+}
+
+/// A control message to a worker's control channel.
+pub enum WorkerControl {
+    /// Begin (or resume) processing batches.
+    Start,
+    /// Stop processing but keep any queued work for the next `Start`.
+    Pause,
+    /// Discard queued work and return to `Idle`.
+    Cancel,
+}
+
+/// A supervised, message-driven worker. One worker owns one job; its lifecycle is driven by
+/// `WorkerControl` messages and its state is observable for introspection.
+pub trait Worker {
+    /// A stable human-readable name, used in introspection listings.
+    fn name(&self) -> ~str;
+    /// The worker's current lifecycle state.
+    fn state(&self) -> WorkerState;
+    /// Sends a control message to the worker.
+    fn control(&self, msg: WorkerControl);
+}
+
+/// A worker that forwards batched tile re-render requests to the rendering task, throttled by
+/// a tranquility sleep so regeneration after a zoom does not flood the renderer.
+pub struct TileFetchWorker {
+    name: ~str,
+    priv control_chan: SharedChan<WorkerControl>,
+    priv work_chan: SharedChan<ReRenderMsg>,
+    priv state: UnsafeArc<AtomicUint>,
+}
+
+impl TileFetchWorker {
+    /// Spawns a tile-fetch worker that dispatches to `render_chan`, sleeping `tranquility_ms`
+    /// between batches. It starts paused (`Idle`); submit work with `submit` and kick it with
+    /// `control(Start)`.
+    pub fn new(name: ~str, render_chan: RenderChan, tranquility_ms: uint) -> TileFetchWorker {
+        let (control_port, control_chan) = comm::stream();
+        let (work_port, work_chan) = comm::stream();
+        let state = UnsafeArc::new(AtomicUint::new(Idle.to_code()));
+
+        let worker_state = state.clone();
+        do spawn {
+            let set_state = |s: WorkerState| unsafe {
+                (*worker_state.get()).store(s.to_code(), Release)
+            };
+            let mut running = false;
+            loop {
+                // Drain any pending control messages first so Pause/Cancel take effect
+                // promptly even in the middle of a long run.
+                while control_port.peek() {
+                    match control_port.recv() {
+                        Start => running = true,
+                        Pause => { running = false; set_state(Idle); }
+                        Cancel => {
+                            running = false;
+                            while work_port.peek() { work_port.recv(); }
+                            set_state(Idle);
+                        }
+                    }
+                }
+
+                if running && work_port.peek() {
+                    set_state(Active);
+                    let batch = work_port.recv();
+                    render_chan.send(batch);
+                    // Tranquility: yield the renderer some breathing room before the next
+                    // batch rather than dispatching the whole queue back to back.
+                    timer::sleep(&uv_global_loop::get(), tranquility_ms);
+                } else {
+                    if running { set_state(Idle); }
+                    // Nothing to do; block until the next control message wakes us.
+                    match control_port.recv() {
+                        Start => running = true,
+                        Pause => { running = false; set_state(Idle); }
+                        Cancel => {
+                            running = false;
+                            while work_port.peek() { work_port.recv(); }
+                            set_state(Idle);
+                        }
+                    }
+                }
+            }
+        }
+
+        TileFetchWorker {
+            name: name,
+            control_chan: SharedChan::new(control_chan),
+            work_chan: SharedChan::new(work_chan),
+            state: state,
+        }
+    }
+
+    /// Queues a batch of tile requests for the worker to dispatch once started.
+    pub fn submit(&self, batch: ReRenderMsg) {
+        self.work_chan.send(batch);
+    }
+}
+
+impl Worker for TileFetchWorker {
+    fn name(&self) -> ~str {
+        self.name.clone()
+    }
+
+    fn state(&self) -> WorkerState {
+        WorkerState::from_code(unsafe { (*self.state.get()).load(Acquire) })
+    }
+
+    fn control(&self, msg: WorkerControl) {
+        self.control_chan.send(msg);
+    }
+}
+
+// -- introspection --
+
+/// Supervises the compositor's workers, mostly so their state can be listed for debugging.
+pub struct WorkerSupervisor {
+    priv workers: ~[@Worker],
+}
+
+impl WorkerSupervisor {
+    /// Creates an empty supervisor.
+    pub fn new() -> WorkerSupervisor {
+        WorkerSupervisor { workers: ~[] }
+    }
+
+    /// Registers a worker to be tracked.
+    pub fn register(&mut self, worker: @Worker) {
+        self.workers.push(worker);
+    }
+
+    /// Lists every tracked worker and its current state, for introspection. Used to answer
+    /// "what is running?" when diagnosing zoom-stutter reports.
+    pub fn list(&self) -> ~[(~str, WorkerState)] {
+        self.workers.iter().map(|w| (w.name(), w.state())).collect()
+    }
+}